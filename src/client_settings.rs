@@ -0,0 +1,112 @@
+//! Holds the configuration and shared HTTP plumbing (API key, rate limiting, retries) that every
+//! `RouteProvider` talking to a remote API should go through, instead of each one baking in its
+//! own key and calling the API unconditionally.
+
+extern crate rest_client;
+
+use rest_client::RestClient;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Configuration for talking to a routing API: the key to authenticate with, how hard we're
+/// allowed to hit it, and how to behave when it pushes back.
+pub struct ClientSettings {
+    /// The API key to send with every request
+    api_key: String,
+    /// The maximum number of requests allowed within `rate_window`
+    rate_limit: usize,
+    /// The time window over which `rate_limit` applies
+    rate_window: Duration,
+    /// How many times to retry a request that fails with a transient (429/5xx) error
+    max_retries: u32,
+    /// The maximum delay between retries; the exponential backoff is capped here
+    max_backoff: Duration,
+    /// Timestamps of requests made within the current rate window
+    request_times: RefCell<VecDeque<Instant>>,
+}
+
+impl ClientSettings {
+    /// Builds a `ClientSettings` from explicit values.
+    pub fn new( api_key: String, rate_limit: usize, rate_window: Duration, max_retries: u32, max_backoff: Duration ) -> ClientSettings {
+        return ClientSettings {
+            api_key:        api_key,
+            rate_limit:     rate_limit,
+            rate_window:    rate_window,
+            max_retries:    max_retries,
+            max_backoff:    max_backoff,
+            request_times:  RefCell::new( VecDeque::new() ),
+        };
+    }
+
+    /// Builds a `ClientSettings` for the given API key, preferring `WAYPLAN_API_KEY` from the
+    /// environment over the key configured in the profile file, with sensible defaults for rate
+    /// limiting and retries.
+    pub fn from_env_or_profile( profile_key: Option<String> ) -> ClientSettings {
+        let api_key = std::env::var( "WAYPLAN_API_KEY" ).ok().or( profile_key ).unwrap_or( String::new() );
+
+        return ClientSettings::new( api_key, 50, Duration::from_secs( 1 ), 5, Duration::from_secs( 30 ) );
+    }
+
+    /// The API key to pass as the `key` parameter of every request.
+    pub fn api_key( &self ) -> &str {
+        return &self.api_key;
+    }
+
+    /// Blocks, if necessary, until another request is allowed under the configured rate limit.
+    fn throttle( &self ) {
+        let mut request_times = self.request_times.borrow_mut();
+        let now = Instant::now();
+
+        while let Some( &oldest ) = request_times.front() {
+            if now.duration_since( oldest ) >= self.rate_window {
+                request_times.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if request_times.len() >= self.rate_limit {
+            let oldest = *request_times.front().unwrap();
+            let wait = self.rate_window - now.duration_since( oldest );
+            thread::sleep( wait );
+        }
+
+        request_times.push_back( Instant::now() );
+    }
+
+    /// Performs a rate-limited GET request, retrying on 429/5xx responses and transport errors
+    /// with exponential backoff up to `max_retries`, capped at `max_backoff` between attempts.
+    pub fn get_with_params( &self, url: &str, params: &[(&str, &str)] ) -> Result<rest_client::RestResponse, String> {
+        let mut delay = Duration::from_millis( 500 );
+        let mut attempt = 0u32;
+
+        loop {
+            self.throttle();
+
+            match RestClient::get_with_params( url, params ) {
+                Ok( response ) => {
+                    if response.status == 429 || response.status >= 500 {
+                        if attempt >= self.max_retries {
+                            return Err( format!( "giving up after {} retries (last status {})", attempt, response.status ) );
+                        }
+                    } else {
+                        return Ok( response );
+                    }
+                },
+                Err( e ) => {
+                    if attempt >= self.max_retries {
+                        return Err( format!( "{:?}", e ) );
+                    }
+                },
+            }
+
+            thread::sleep( delay );
+            delay = std::cmp::min( delay * 2, self.max_backoff );
+            attempt += 1;
+        }
+    }
+}
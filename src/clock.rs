@@ -0,0 +1,65 @@
+//! Helpers for converting between `HH:MM` wall-clock times and the second-based arithmetic used
+//! to chain leg durations into an absolute schedule.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Parses a clock time in `HH:MM` format into the number of seconds since midnight.
+pub fn seconds_since_midnight( clock: &str ) -> Result<i64, String> {
+    let mut parts = clock.splitn( 2, ':' );
+
+    let hours: i64   = try!( parts.next().ok_or( "missing hours".to_string() )
+        .and_then( |s| s.parse().map_err( |_| format!( "invalid hours '{}'", s ) ) ) );
+    let minutes: i64 = try!( parts.next().ok_or( "missing minutes".to_string() )
+        .and_then( |s| s.parse().map_err( |_| format!( "invalid minutes '{}'", s ) ) ) );
+
+    if hours < 0 || hours > 23 || minutes < 0 || minutes > 59 {
+        return Err( format!( "'{}' is not a valid HH:MM clock time", clock ) );
+    }
+
+    return Ok( hours * 3600 + minutes * 60 );
+}
+
+/// Formats a number of seconds since midnight back into `HH:MM`, wrapping around midnight.
+pub fn format_clock( seconds_since_midnight: i64 ) -> String {
+    let wrapped = ( ( seconds_since_midnight % 86400 ) + 86400 ) % 86400;
+
+    return format!( "{:02}:{:02}", wrapped / 3600, ( wrapped % 3600 ) / 60 );
+}
+
+/// Turns a number of seconds since midnight into an epoch timestamp for today, so it can be
+/// passed to a `RouteProvider` as a `departure_time`.
+///
+/// This works in UTC rather than the caller's local time zone, which is good enough for
+/// estimating which traffic model a routing API picks but should not be relied on for anything
+/// that needs a real calendar.
+pub fn epoch_for_seconds_since_midnight( seconds_since_midnight: i64 ) -> i64 {
+    let now: i64 = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_secs() as i64;
+    let today_midnight = now - ( now % 86400 );
+
+    return today_midnight + seconds_since_midnight;
+}
+
+#[cfg( test )]
+mod tests {
+    use super::{seconds_since_midnight, format_clock};
+
+    #[test]
+    fn parses_and_formats_a_clock_time() {
+        assert_eq!( seconds_since_midnight( "09:30" ).unwrap(), 9 * 3600 + 30 * 60 );
+        assert_eq!( format_clock( 9 * 3600 + 30 * 60 ), "09:30" );
+    }
+
+    #[test]
+    fn rejects_an_out_of_range_clock_time() {
+        assert!( seconds_since_midnight( "24:00" ).is_err() );
+        assert!( seconds_since_midnight( "12:60" ).is_err() );
+    }
+
+    #[test]
+    fn wraps_around_midnight() {
+        // A duration that carries a leg's arrival past midnight should wrap back to 00:xx
+        // instead of printing an hour count past 23.
+        assert_eq!( format_clock( 23 * 3600 + 50 * 60 + 20 * 60 ), "00:10" );
+        assert_eq!( format_clock( -10 * 60 ), "23:50" );
+    }
+}
@@ -0,0 +1,182 @@
+//! Imports scheduled leg durations from a GTFS feed (`stops.txt`, `stop_times.txt`,
+//! `trips.txt`), so `usual_timetable_duration` can be derived from the real published schedule
+//! instead of being hand-entered and going stale.
+
+extern crate csv;
+
+use std::collections::{HashMap, HashSet};
+
+/// One row of `stop_times.txt` relevant to computing a scheduled duration.
+struct StopTime {
+    /// The id of the stop visited
+    stop_id: String,
+    /// The position of this stop within its trip
+    stop_sequence: u32,
+    /// The scheduled arrival time, in seconds since midnight
+    arrival: i64,
+    /// The scheduled departure time, in seconds since midnight
+    departure: i64,
+}
+
+/// A parsed GTFS feed, indexed for looking up the scheduled duration between two named stops.
+pub struct GtfsFeed {
+    /// Maps a stop's human-readable name to its GTFS stop id
+    stops_by_name: HashMap<String, String>,
+    /// Maps a trip id to the stop times visited on that trip
+    stop_times_by_trip: HashMap<String, Vec<StopTime>>,
+}
+
+impl GtfsFeed {
+    /// Loads a GTFS feed from the `stops.txt`, `stop_times.txt` and `trips.txt` files in `dir`.
+    pub fn load( dir: &str ) -> Result<GtfsFeed, String> {
+        let stops_by_name  = try!( load_stops( &format!( "{}/stops.txt", dir ) ) );
+        let valid_trips    = try!( load_trip_ids( &format!( "{}/trips.txt", dir ) ) );
+        let stop_times_by_trip = try!( load_stop_times( &format!( "{}/stop_times.txt", dir ), &valid_trips ) );
+
+        return Ok( GtfsFeed { stops_by_name: stops_by_name, stop_times_by_trip: stop_times_by_trip } );
+    }
+
+    /// Returns the scheduled duration, in seconds, between `origin_name` and `destination_name`.
+    ///
+    /// When more than one trip serves the pair (a local and an express service, say), the
+    /// earliest-departing trip is used, with ties broken by trip id. `stop_times_by_trip` is a
+    /// `HashMap`, whose iteration order isn't stable across runs, so picking "whichever trip
+    /// comes first" there would make re-running the import against an unchanged feed silently
+    /// produce a different duration each time.
+    pub fn scheduled_duration( &self, origin_name: &str, destination_name: &str ) -> Result<i64, String> {
+        let origin_id      = try!( self.stops_by_name.get( origin_name ).ok_or( format!( "unknown GTFS stop '{}'", origin_name ) ) );
+        let destination_id = try!( self.stops_by_name.get( destination_name ).ok_or( format!( "unknown GTFS stop '{}'", destination_name ) ) );
+
+        // (trip_id, origin departure, duration) for every trip that visits the origin before the
+        // destination
+        let mut candidates: Vec<(&String, i64, i64)> = Vec::new();
+
+        for ( trip_id, entries ) in &self.stop_times_by_trip {
+            let origin_entry      = entries.iter().find( |e| &e.stop_id == origin_id );
+            let destination_entry = entries.iter().find( |e| &e.stop_id == destination_id );
+
+            if let ( Some( o ), Some( d ) ) = ( origin_entry, destination_entry ) {
+                if o.stop_sequence < d.stop_sequence {
+                    candidates.push( ( trip_id, o.departure, d.arrival - o.departure ) );
+                }
+            }
+        }
+
+        candidates.sort_by( |a, b| a.1.cmp( &b.1 ).then_with( || a.0.cmp( b.0 ) ) );
+
+        return match candidates.first() {
+            Some( &( _, _, duration ) ) => Ok( duration ),
+            None                        => Err( format!( "no GTFS trip found running from '{}' to '{}'", origin_name, destination_name ) ),
+        };
+    }
+}
+
+/// Parses a GTFS `H:MM:SS` time (hours may exceed 23 for trips past midnight) into seconds.
+fn parse_gtfs_time( time: &str ) -> Result<i64, String> {
+    let parts: Vec<&str> = time.trim().splitn( 3, ':' ).collect();
+
+    if parts.len() != 3 {
+        return Err( format!( "invalid GTFS time '{}'", time ) );
+    }
+
+    let hours: i64   = try!( parts[0].parse().map_err( |_| format!( "invalid GTFS time '{}'", time ) ) );
+    let minutes: i64 = try!( parts[1].parse().map_err( |_| format!( "invalid GTFS time '{}'", time ) ) );
+    let seconds: i64 = try!( parts[2].parse().map_err( |_| format!( "invalid GTFS time '{}'", time ) ) );
+
+    return Ok( hours * 3600 + minutes * 60 + seconds );
+}
+
+/// Finds the index of a column by name in a row of CSV headers.
+fn index_of( headers: &[String], name: &str ) -> Result<usize, String> {
+    return headers.iter().position( |h| h == name ).ok_or( format!( "missing column '{}'", name ) );
+}
+
+/// Builds a stop name -> stop id lookup from `stops.txt`.
+fn load_stops( path: &str ) -> Result<HashMap<String, String>, String> {
+    let mut reader = try!( csv::Reader::from_file( path ).map_err( |e| format!( "{:?}", e ) ) );
+    let headers    = try!( reader.headers().map_err( |e| format!( "{:?}", e ) ) );
+    let id_index    = try!( index_of( &headers, "stop_id" ) );
+    let name_index  = try!( index_of( &headers, "stop_name" ) );
+
+    let mut stops_by_name = HashMap::new();
+
+    for row in reader.records() {
+        let row = try!( row.map_err( |e| format!( "{:?}", e ) ) );
+        stops_by_name.insert( row[name_index].clone(), row[id_index].clone() );
+    }
+
+    return Ok( stops_by_name );
+}
+
+/// Collects the set of trip ids declared in `trips.txt`, so `stop_times.txt` rows for trips that
+/// no longer exist in the feed are ignored.
+fn load_trip_ids( path: &str ) -> Result<HashSet<String>, String> {
+    let mut reader = try!( csv::Reader::from_file( path ).map_err( |e| format!( "{:?}", e ) ) );
+    let headers    = try!( reader.headers().map_err( |e| format!( "{:?}", e ) ) );
+    let trip_index  = try!( index_of( &headers, "trip_id" ) );
+
+    let mut trip_ids = HashSet::new();
+
+    for row in reader.records() {
+        let row = try!( row.map_err( |e| format!( "{:?}", e ) ) );
+        trip_ids.insert( row[trip_index].clone() );
+    }
+
+    return Ok( trip_ids );
+}
+
+/// Groups `stop_times.txt` rows by trip id, keeping only the fields needed to derive a
+/// scheduled duration, and skipping rows for trips not present in `trips.txt`.
+fn load_stop_times( path: &str, valid_trips: &HashSet<String> ) -> Result<HashMap<String, Vec<StopTime>>, String> {
+    let mut reader = try!( csv::Reader::from_file( path ).map_err( |e| format!( "{:?}", e ) ) );
+    let headers    = try!( reader.headers().map_err( |e| format!( "{:?}", e ) ) );
+    let trip_index       = try!( index_of( &headers, "trip_id" ) );
+    let arrival_index    = try!( index_of( &headers, "arrival_time" ) );
+    let departure_index  = try!( index_of( &headers, "departure_time" ) );
+    let stop_index       = try!( index_of( &headers, "stop_id" ) );
+    let sequence_index   = try!( index_of( &headers, "stop_sequence" ) );
+
+    let mut stop_times_by_trip: HashMap<String, Vec<StopTime>> = HashMap::new();
+
+    for row in reader.records() {
+        let row = try!( row.map_err( |e| format!( "{:?}", e ) ) );
+        let trip_id = row[trip_index].clone();
+
+        if !valid_trips.contains( &trip_id ) {
+            continue;
+        }
+
+        let stop_time = StopTime {
+            stop_id:        row[stop_index].clone(),
+            stop_sequence:  try!( row[sequence_index].parse().map_err( |_| format!( "invalid stop_sequence '{}'", row[sequence_index] ) ) ),
+            arrival:        try!( parse_gtfs_time( &row[arrival_index] ) ),
+            departure:      try!( parse_gtfs_time( &row[departure_index] ) ),
+        };
+
+        stop_times_by_trip.entry( trip_id ).or_insert_with( Vec::new ).push( stop_time );
+    }
+
+    return Ok( stop_times_by_trip );
+}
+
+#[cfg( test )]
+mod tests {
+    use super::parse_gtfs_time;
+
+    #[test]
+    fn parses_a_normal_time() {
+        assert_eq!( parse_gtfs_time( "08:05:00" ).unwrap(), 8 * 3600 + 5 * 60 );
+    }
+
+    #[test]
+    fn parses_a_past_midnight_time() {
+        // GTFS allows hours past 23 for trips that run past midnight, counted from the start of
+        // the service day rather than wrapping back to 00:xx.
+        assert_eq!( parse_gtfs_time( "25:30:00" ).unwrap(), 25 * 3600 + 30 * 60 );
+    }
+
+    #[test]
+    fn rejects_a_malformed_time() {
+        assert!( parse_gtfs_time( "08:05" ).is_err() );
+    }
+}
@@ -1,54 +1,23 @@
 //! This module defines a program which calculates the expected traffic delay on a certain path
-//! using the Google Maps Directions API. This can be used to calculate the extra amount of time
-//! you need on your way to work, for example.
+//! using a pluggable routing provider named in the profile (currently only Google Maps). This can be used to
+//! calculate the extra amount of time you need on your way to work, for example.
 
 extern crate rest_client;
 extern crate rustc_serialize;
 
-use rest_client::RestClient;
-use rustc_serialize::json::Json;
-
 use std::io::prelude::*;
 use std::fs::File;
 
-/// A function which returns the amount of time it will take to travel from origin to destination
-/// via specified waypoints by car, according to the Google Maps Directions API.
-/// Multiple waypoints are to be separated by | pipe characters, or ultimately as specified by the
-/// Google Maps Directions API.
-fn get_duration( origin: &str, destination: &str, waypoints: &str ) -> i64 {
-    let mut result: i64 = 0i64;
-
-    // Get API response
-    let response = RestClient::get_with_params( 
-        "https://maps.googleapis.com/maps/api/directions/json", 
-        &[  ("origin", origin), 
-            ("destination", destination), 
-            ("waypoints", waypoints),
-            ("departure_time", "now"),
-            ("traffic_model", "best_guess"),
-            ("mode", "driving"),
-            ("key", "" ) ] ).unwrap();
-
-    // Travel down the json tree, retrieve the array saved in
-    // DOC -> routes[0] -> legs
-    let response_json   = Json::from_str( &response.body ).unwrap();
-    let routes          = response_json.search( "routes" ).unwrap();
-    let first_route     = routes.as_array().unwrap()[0].as_object().unwrap();
-    let leg_array       = first_route.get( "legs" ).unwrap().as_array().unwrap();
-
-    // Go through all array entries and accumulate the times for this route
-    for leg in leg_array {
-        // Travel even further down the json tree to get the duration of the leg
-        let leg_object = leg.as_object().unwrap();
-        let duration = leg_object.get( "duration_in_traffic" ).unwrap();
-        let value = duration.as_object().unwrap().get( "value" ).unwrap();
-
-        // Add it to the accumulator
-        result += value.as_i64().unwrap();
-    }
+mod client_settings;
+mod clock;
+mod gtfs;
+mod polyline;
+mod route_provider;
 
-    return result;
-}
+use client_settings::ClientSettings;
+use route_provider::{RouteProvider, RouteRequest};
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
 
 /// Returns a string containing the encoding of a given amount of seconds in -M:SS format.
 fn get_minute_string( seconds: i64 ) -> String {
@@ -91,20 +60,59 @@ struct Leg {
     usual_internal_duration: i64,
     /// The duration of this leg according to the timetable
     usual_timetable_duration: i64,
+    /// The mode of travel for this leg: `driving`, `walking`, `bicycling` or `transit`
+    mode: String,
+    /// The preferred type of transit vehicle (`bus`, `rail`, `subway`, ...), only meaningful
+    /// when `mode` is `transit`
+    transit_mode: Option<String>,
+    /// The desired departure time for this leg, as RFC3339 or an epoch timestamp
+    departure_time: Option<String>,
+    /// The desired arrival time for this leg, as RFC3339 or an epoch timestamp. When set, the
+    /// provider plans backward from it instead of using `departure_time`
+    arrival_time: Option<String>,
 }
 
 impl Leg {
-    /// Gets the duration of this leg using a call to the Google Maps Directions API.
-    fn duration( &self ) -> i64 {
-        return get_duration( &self.origin.internal, &self.destination.internal, &self.via.internal ) + ( self.usual_timetable_duration - self.usual_internal_duration );
+    /// Builds the `RouteRequest` a `RouteProvider` needs to answer for this leg.
+    fn request( &self ) -> RouteRequest {
+        return RouteRequest {
+            origin:         &self.origin.internal,
+            destination:    &self.destination.internal,
+            via:            &self.via.internal,
+            mode:           &self.mode,
+            transit_mode:   self.transit_mode.as_ref().map( |s| &s[..] ),
+            departure_time: self.departure_time.as_ref().map( |s| &s[..] ),
+            arrival_time:   self.arrival_time.as_ref().map( |s| &s[..] ),
+        };
+    }
+
+    /// Turns a travel time predicted by a `RouteProvider` into this leg's total duration,
+    /// including the fixed deviation between its internal and timetable durations.
+    fn duration_from_travel_time( &self, travel_time: i64 ) -> i64 {
+        return travel_time + ( self.usual_timetable_duration - self.usual_internal_duration );
     }
 }
 
+/// A profile describing which routing provider to use and the legs of the journey to plan.
+#[derive( RustcDecodable, RustcEncodable )]
+struct Profile {
+    /// The name of the `RouteProvider` to use, e.g. `"google_maps"`
+    provider: String,
+    /// The API key to use, if not supplied via the `WAYPLAN_API_KEY` environment variable
+    api_key: Option<String>,
+    /// The clock time, in `HH:MM` format, at which to depart on the first leg. When set, the
+    /// output chains each leg's computed duration into absolute departure and arrival times
+    /// instead of just reporting a deviation from the timetable.
+    start_time: Option<String>,
+    /// The legs making up this journey
+    legs: Vec<Leg>,
+}
+
 /// Gets the current profile.
 ///
 /// Based on whether arguments have been passed to the program, loads a suitable json file for
-/// populating the Leg vector.
-fn get_profile() -> Vec<Leg> {
+/// populating the Profile.
+fn get_profile() -> Profile {
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() > 1 {
@@ -114,8 +122,8 @@ fn get_profile() -> Vec<Leg> {
     }
 }
 
-/// Loads an array of legs from the given file name, where the data is stored in JSON.
-fn load_profile( filename: &str ) -> Vec<Leg> {
+/// Loads a profile from the given file name, where the data is stored in JSON.
+fn load_profile( filename: &str ) -> Profile {
     let mut content: String = String::new();
     let mut file = File::open(filename).unwrap();
     file.read_to_string( &mut content ).unwrap();
@@ -123,21 +131,167 @@ fn load_profile( filename: &str ) -> Vec<Leg> {
     return rustc_serialize::json::decode(&content[..]).unwrap();
 }
 
-/// The entry point of wayplan. Calls the Google Maps API and prints the result to the console
-/// output.
+/// Prints every leg's predicted duration and its deviation from the timetable, fetching all
+/// legs' travel times in one go so an N-leg profile doesn't cost N round trips.
+fn print_plan( legs: &[Leg], provider: &RouteProvider ) {
+    let requests: Vec<RouteRequest> = legs.iter().map( |leg| leg.request() ).collect();
+    let travel_times = provider.leg_durations( &requests ).unwrap();
+
+    for ( leg, &travel_time ) in legs.iter().zip( travel_times.iter() ) {
+        let duration = leg.duration_from_travel_time( travel_time );
+
+        println!( "{}: {} -> {}", leg.description, leg.origin.description, leg.destination.description );
+        println!( "    Predicted duration: {} min (deviation {} min)", get_minute_string( duration ), get_minute_string( duration - leg.usual_timetable_duration ) );
+        println!( "" );
+    }
+}
+
+/// Prints every leg's absolute departure and arrival clock times, chaining leg 0's arrival
+/// (computed from `start_time`) into leg 1's departure, and so on down the chain. Each leg's
+/// computed departure is passed back into the routing provider as `departure_time`, so its
+/// traffic prediction reflects the actual time you'd be on that segment.
+fn print_schedule( legs: &[Leg], provider: &RouteProvider, start_time: &str ) {
+    let mut clock = clock::seconds_since_midnight( start_time ).unwrap();
+
+    for leg in legs {
+        let departure_clock = clock;
+        let epoch = clock::epoch_for_seconds_since_midnight( departure_clock ).to_string();
+
+        let mut request = leg.request();
+
+        // A chained departure time and a fixed arrival_time are mutually exclusive ways to plan
+        // a leg; the chain wins, but say so instead of silently dropping the leg's own setting.
+        if leg.arrival_time.is_some() {
+            println!( "{}: ignoring configured arrival_time, chaining a departure time from start_time instead", leg.description );
+        }
+
+        request.departure_time = Some( &epoch );
+        request.arrival_time = None;
+
+        let travel_time = provider.leg_duration( &request ).unwrap();
+        let duration = leg.duration_from_travel_time( travel_time );
+        let arrival_clock = departure_clock + duration;
+
+        println!( "{}: {} -> {}", leg.description, leg.origin.description, leg.destination.description );
+        println!( "    {} \u{2192} {} ({} min, {:+} min vs timetable)",
+            clock::format_clock( departure_clock ),
+            clock::format_clock( arrival_clock ),
+            duration / 60,
+            ( duration - leg.usual_timetable_duration ) / 60 );
+        println!( "" );
+
+        clock = arrival_clock;
+    }
+}
+
+/// Builds a GeoJSON `Feature` containing a leg's route geometry as a `LineString`, with its
+/// description and predicted duration as properties.
+fn leg_to_geojson_feature( leg: &Leg, coordinates: &[(f64, f64)], duration: i64 ) -> Json {
+    let mut geometry = BTreeMap::new();
+    geometry.insert( "type".to_string(), Json::String( "LineString".to_string() ) );
+    geometry.insert( "coordinates".to_string(), Json::Array(
+        coordinates.iter().map( |&(lat, lng)| Json::Array( vec![ Json::F64( lng ), Json::F64( lat ) ] ) ).collect()
+    ) );
+
+    let mut properties = BTreeMap::new();
+    properties.insert( "description".to_string(), Json::String( leg.description.clone() ) );
+    properties.insert( "predicted_duration_seconds".to_string(), Json::I64( duration ) );
+
+    let mut feature = BTreeMap::new();
+    feature.insert( "type".to_string(), Json::String( "Feature".to_string() ) );
+    feature.insert( "geometry".to_string(), Json::Object( geometry ) );
+    feature.insert( "properties".to_string(), Json::Object( properties ) );
+
+    return Json::Object( feature );
+}
+
+/// Prints the plan as a GeoJSON `FeatureCollection`, with one `LineString` feature per leg, so
+/// it can be visualised on a map instead of only printed as text.
+fn print_geojson( legs: &[Leg], provider: &RouteProvider ) {
+    let mut features = Vec::new();
+
+    for leg in legs {
+        let request = leg.request();
+        let ( travel_time, coordinates ) = provider.leg_duration_and_geometry( &request ).unwrap();
+        let duration = leg.duration_from_travel_time( travel_time );
+
+        features.push( leg_to_geojson_feature( leg, &coordinates, duration ) );
+    }
+
+    let mut collection = BTreeMap::new();
+    collection.insert( "type".to_string(), Json::String( "FeatureCollection".to_string() ) );
+    collection.insert( "features".to_string(), Json::Array( features ) );
+
+    println!( "{}", Json::Object( collection ) );
+}
+
+/// Re-derives every leg's `usual_timetable_duration` from a GTFS feed and writes the profile
+/// back out, so the "deviation vs timetable" figure reflects the real published schedule
+/// instead of a hand-entered guess.
+fn import_gtfs_timetables( gtfs_dir: &str, profile_path: &str ) {
+    let mut profile = load_profile( profile_path );
+    let feed = match gtfs::GtfsFeed::load( gtfs_dir ) {
+        Ok( feed ) => feed,
+        Err( e )   => {
+            println!( "Could not load GTFS feed from '{}': {}", gtfs_dir, e );
+            return;
+        },
+    };
+
+    for leg in &mut profile.legs {
+        match feed.scheduled_duration( &leg.origin.description, &leg.destination.description ) {
+            Ok( duration ) => leg.usual_timetable_duration = duration,
+            Err( e )       => println!( "Skipping '{}': {}", leg.description, e ),
+        }
+    }
+
+    let encoded = rustc_serialize::json::as_pretty_json( &profile ).to_string();
+    let mut file = File::create( profile_path ).unwrap();
+    file.write_all( encoded.as_bytes() ).unwrap();
+}
+
+/// The entry point of wayplan. Calls the configured routing provider and prints the result to
+/// the console output, or, when invoked as `wayplan --import-gtfs <gtfs_dir> [profile.json]`,
+/// imports scheduled durations from a GTFS feed into the profile instead.
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 1 && args[1] == "--import-gtfs" {
+        let gtfs_dir = match args.get( 2 ) {
+            Some( dir ) => dir,
+            None        => {
+                println!( "usage: wayplan --import-gtfs <gtfs_dir> [profile.json]" );
+                return;
+            },
+        };
+        let profile_path = args.get( 3 ).map( |s| &s[..] ).unwrap_or( "profile.json" );
+
+        import_gtfs_timetables( gtfs_dir, profile_path );
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "--geojson" {
+        let profile_path = args.get( 2 ).map( |s| &s[..] ).unwrap_or( "profile.json" );
+        let profile  = load_profile( profile_path );
+        let settings = ClientSettings::from_env_or_profile( profile.api_key.clone() );
+        let provider = route_provider::provider_from_name( &profile.provider, settings ).unwrap();
+
+        print_geojson( &profile.legs, &*provider );
+        return;
+    }
+
     // Gets the profile which contains the route information
     let profile = get_profile();
+    let settings = ClientSettings::from_env_or_profile( profile.api_key.clone() );
+    let provider = route_provider::provider_from_name( &profile.provider, settings ).unwrap();
 
-    // Print loop
-    for x in 0..profile.len() {
-        // Takes one leg from the profile
-        let ref leg = profile[x];
+    // A start time, passed as a second CLI argument after the profile path, overrides the one in
+    // the profile
+    let start_time = args.get( 2 ).cloned().or( profile.start_time.clone() );
 
-        // Prints the result
-        println!( "{}: {} -> {}", leg.description, leg.origin.description, leg.destination.description );
-        println!( "    Predicted duration: {} min (deviation {} min)", get_minute_string( leg.duration() ), get_minute_string( leg.duration() - leg.usual_timetable_duration ) );
-        println!( "" );
+    match start_time {
+        Some( start_time ) => print_schedule( &profile.legs, &*provider, &start_time ),
+        None                => print_plan( &profile.legs, &*provider ),
     }
 }
 
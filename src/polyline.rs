@@ -0,0 +1,69 @@
+//! Decodes Google's encoded polyline algorithm format, as used by the `overview_polyline` field
+//! of a Directions API route, into a list of (latitude, longitude) coordinates.
+
+/// Decodes an encoded polyline string into a list of `(latitude, longitude)` pairs.
+pub fn decode( encoded: &str ) -> Result<Vec<(f64, f64)>, String> {
+    let bytes = encoded.as_bytes();
+    let mut index = 0;
+    let mut lat: i64 = 0;
+    let mut lng: i64 = 0;
+    let mut coordinates = Vec::new();
+
+    while index < bytes.len() {
+        lat += try!( decode_signed_value( bytes, &mut index ) );
+        lng += try!( decode_signed_value( bytes, &mut index ) );
+
+        coordinates.push( ( lat as f64 / 1e5, lng as f64 / 1e5 ) );
+    }
+
+    return Ok( coordinates );
+}
+
+/// Decodes one varint-encoded, zig-zag signed delta starting at `*index`, advancing `*index`
+/// past it.
+fn decode_signed_value( bytes: &[u8], index: &mut usize ) -> Result<i64, String> {
+    let mut result: i64 = 0;
+    let mut shift: u32 = 0;
+
+    loop {
+        if *index >= bytes.len() {
+            return Err( "truncated polyline".to_string() );
+        }
+
+        let byte = bytes[*index] as i64 - 63;
+        *index += 1;
+
+        result |= ( byte & 0x1f ) << shift;
+        shift += 5;
+
+        if byte & 0x20 == 0 {
+            break;
+        }
+    }
+
+    // The value was stored zig-zag encoded: odd values are negative.
+    if result & 1 != 0 {
+        return Ok( !( result >> 1 ) );
+    } else {
+        return Ok( result >> 1 );
+    }
+}
+
+#[cfg( test )]
+mod tests {
+    use super::decode;
+
+    #[test]
+    fn decodes_the_canonical_google_example() {
+        // From Google's polyline algorithm documentation: encodes
+        // (38.5, -120.2), (40.7, -120.95), (43.252, -126.453)
+        let coordinates = decode( "_p~iF~ps|U_ulLnnqC_mqNvxq`@" ).unwrap();
+
+        assert_eq!( coordinates, vec![ ( 38.5, -120.2 ), ( 40.7, -120.95 ), ( 43.252, -126.453 ) ] );
+    }
+
+    #[test]
+    fn rejects_a_truncated_polyline() {
+        assert!( decode( "_p~iF~ps|U_ulLnnqC_mqNvxq" ).is_err() );
+    }
+}
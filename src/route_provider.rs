@@ -0,0 +1,363 @@
+//! Defines the `RouteProvider` trait, the extension point that lets wayplan compute leg
+//! durations from different routing back ends instead of being hardcoded against one paid API.
+
+extern crate rustc_serialize;
+
+use rustc_serialize::json::Json;
+
+use std::collections::HashMap;
+
+use client_settings::ClientSettings;
+use polyline;
+
+/// The parameters of a single leg, passed to a `RouteProvider` to obtain a duration prediction.
+pub struct RouteRequest<'a> {
+    /// The origin of the leg, in a format understood by the provider
+    pub origin: &'a str,
+    /// The destination of the leg, in a format understood by the provider
+    pub destination: &'a str,
+    /// One or more waypoints on the way, in a format understood by the provider
+    pub via: &'a str,
+    /// The mode of travel: `driving`, `walking`, `bicycling` or `transit`
+    pub mode: &'a str,
+    /// The preferred type of transit vehicle (`bus`, `rail`, `subway`, ...), only meaningful
+    /// when `mode` is `transit`
+    pub transit_mode: Option<&'a str>,
+    /// The desired departure time, as RFC3339 or an epoch timestamp
+    pub departure_time: Option<&'a str>,
+    /// The desired arrival time, as RFC3339 or an epoch timestamp; when set, the provider should
+    /// plan backward from it instead of using `departure_time`
+    pub arrival_time: Option<&'a str>,
+}
+
+/// A source of travel-time predictions for a leg of a journey.
+///
+/// Implementations are free to call out to whatever routing service they like; the only
+/// requirement is that they can turn a `RouteRequest` into a duration in seconds. This is what
+/// lets wayplan swap the Google Maps Directions API for a self-hosted Valhalla or
+/// OpenTripPlanner instance, or a mock used in tests.
+pub trait RouteProvider {
+    /// Returns the predicted duration, in seconds, of travelling the given request.
+    fn leg_duration( &self, request: &RouteRequest ) -> Result<i64, String>;
+
+    /// Returns the predicted duration, in seconds, of each of the given requests, in order.
+    ///
+    /// The default implementation just calls `leg_duration` once per request; providers that can
+    /// answer several requests in one round trip (such as Google's Distance Matrix API) should
+    /// override this for better latency and quota usage.
+    fn leg_durations( &self, requests: &[RouteRequest] ) -> Result<Vec<i64>, String> {
+        let mut result = Vec::with_capacity( requests.len() );
+
+        for request in requests {
+            result.push( try!( self.leg_duration( request ) ) );
+        }
+
+        return Ok( result );
+    }
+
+    /// Returns the route geometry of the given request as a list of `(latitude, longitude)`
+    /// coordinates, for visualising the plan on a map.
+    ///
+    /// The default implementation reports that geometry isn't available; providers backed by an
+    /// API that returns route shapes (such as Google's encoded `overview_polyline`) should
+    /// override this.
+    fn leg_geometry( &self, _request: &RouteRequest ) -> Result<Vec<(f64, f64)>, String> {
+        return Err( "this route provider does not support route geometry".to_string() );
+    }
+
+    /// Returns both the predicted duration and the route geometry of the given request.
+    ///
+    /// The default implementation just calls `leg_duration` and `leg_geometry` separately;
+    /// providers that can answer both from a single response (such as one Directions API call)
+    /// should override this so callers that need both don't pay for two round trips, and so the
+    /// geometry they get back is guaranteed to describe the same route the duration came from.
+    fn leg_duration_and_geometry( &self, request: &RouteRequest ) -> Result<(i64, Vec<(f64, f64)>), String> {
+        let duration = try!( self.leg_duration( request ) );
+        let geometry = try!( self.leg_geometry( request ) );
+
+        return Ok( ( duration, geometry ) );
+    }
+}
+
+/// A `RouteProvider` backed by the Google Maps Directions and Distance Matrix APIs.
+pub struct GoogleMapsProvider {
+    /// The API key, rate limit and retry policy to use for every request
+    settings: ClientSettings,
+}
+
+impl RouteProvider for GoogleMapsProvider {
+    fn leg_duration( &self, request: &RouteRequest ) -> Result<i64, String> {
+        let ( duration, _ ) = try!( self.directions( request, false ) );
+        return Ok( duration );
+    }
+
+    fn leg_durations( &self, requests: &[RouteRequest] ) -> Result<Vec<i64>, String> {
+        let mut results = vec![0i64; requests.len()];
+
+        // Legs with via waypoints aren't supported by the Distance Matrix endpoint, so they fall
+        // back to one Directions call each. Everything else is grouped by the parameters that
+        // have to be shared across a single matrix request (mode, transit mode, departure or
+        // arrival time) and answered in one round trip per group.
+        let mut fallback: Vec<usize> = Vec::new();
+        let mut groups: HashMap<(&str, &str, &str, &str), Vec<usize>> = HashMap::new();
+
+        for (i, request) in requests.iter().enumerate() {
+            if !request.via.is_empty() {
+                fallback.push( i );
+                continue;
+            }
+
+            let key = (
+                request.mode,
+                request.transit_mode.unwrap_or( "" ),
+                request.departure_time.unwrap_or( "" ),
+                request.arrival_time.unwrap_or( "" ),
+            );
+
+            groups.entry( key ).or_insert_with( Vec::new ).push( i );
+        }
+
+        for ( _, indices ) in groups {
+            let group: Vec<&RouteRequest> = indices.iter().map( |&i| &requests[i] ).collect();
+            let durations = try!( self.distance_matrix( &group ) );
+
+            for ( &i, duration ) in indices.iter().zip( durations ) {
+                results[i] = duration;
+            }
+        }
+
+        for i in fallback {
+            results[i] = try!( self.leg_duration( &requests[i] ) );
+        }
+
+        return Ok( results );
+    }
+
+    fn leg_geometry( &self, request: &RouteRequest ) -> Result<Vec<(f64, f64)>, String> {
+        let ( _, geometry ) = try!( self.directions( request, true ) );
+        return Ok( geometry );
+    }
+
+    fn leg_duration_and_geometry( &self, request: &RouteRequest ) -> Result<(i64, Vec<(f64, f64)>), String> {
+        return self.directions( request, true );
+    }
+}
+
+impl GoogleMapsProvider {
+    /// Builds a `GoogleMapsProvider` that talks to the API through the given client settings.
+    pub fn new( settings: ClientSettings ) -> GoogleMapsProvider {
+        return GoogleMapsProvider { settings: settings };
+    }
+
+    /// Makes one Directions API call for `request` and extracts its duration, decoding the
+    /// route's `overview_polyline` as well when `want_geometry` is set. Used by `leg_duration`,
+    /// `leg_geometry` and `leg_duration_and_geometry` alike, so the geometry a caller gets back
+    /// always describes the same route the duration was computed from.
+    fn directions( &self, request: &RouteRequest, want_geometry: bool ) -> Result<(i64, Vec<(f64, f64)>), String> {
+        let mut result: i64 = 0i64;
+
+        let mut params: Vec<(&str, &str)> = vec![
+            ("origin", request.origin),
+            ("destination", request.destination),
+            ("waypoints", request.via),
+            ("mode", request.mode),
+            ("key", self.settings.api_key() ),
+        ];
+
+        let ( time_params, duration_field ) = time_and_mode_params( request );
+        params.extend( time_params );
+
+        // Get API response
+        let response = try!( self.settings.get_with_params(
+            "https://maps.googleapis.com/maps/api/directions/json",
+            &params ) );
+
+        // Travel down the json tree, retrieve the array saved in
+        // DOC -> routes[0] -> legs
+        let response_json  = try!( Json::from_str( &response.body ).map_err( |e| format!( "{:?}", e ) ) );
+        let routes          = try!( response_json.search( "routes" ).ok_or( "no routes in response".to_string() ) );
+        let first_route     = try!( routes.as_array().and_then( |a| a.get( 0 ) ).and_then( |r| r.as_object() ).ok_or( "malformed route".to_string() ) );
+        let leg_array       = try!( first_route.get( "legs" ).and_then( |l| l.as_array() ).ok_or( "malformed legs".to_string() ) );
+
+        // Go through all array entries and accumulate the times for this route
+        for leg in leg_array {
+            // Travel even further down the json tree to get the duration of the leg
+            let leg_object  = try!( leg.as_object().ok_or( "malformed leg".to_string() ) );
+            let duration    = try!( leg_object.get( duration_field ).ok_or( format!( "missing {}", duration_field ) ) );
+            let value       = try!( duration.as_object().and_then( |d| d.get( "value" ) ).and_then( |v| v.as_i64() ).ok_or( "missing duration value".to_string() ) );
+
+            // Add it to the accumulator
+            result += value;
+        }
+
+        let geometry = if want_geometry {
+            let points = try!( first_route.get( "overview_polyline" )
+                .and_then( |p| p.as_object() )
+                .and_then( |p| p.get( "points" ) )
+                .and_then( |p| p.as_string() )
+                .ok_or( "missing overview_polyline".to_string() ) );
+
+            try!( polyline::decode( points ) )
+        } else {
+            Vec::new()
+        };
+
+        return Ok( ( result, geometry ) );
+    }
+
+    /// Looks up the durations of a batch of requests that all share the same mode, transit mode,
+    /// departure time and arrival time, in one Distance Matrix API call.
+    ///
+    /// Google bills Distance Matrix by origin x destination element, and only the matching
+    /// origin/destination pairs (the diagonal of the `origins` x `destinations` grid built below)
+    /// are ever read back -- there is no way to ask the endpoint for just the diagonal. So an
+    /// N-request batch costs N^2 billed elements, not N. That's still fewer round trips than one
+    /// Directions call per leg, but for a large batch it can cost *more* quota than calling
+    /// `leg_duration` per leg would; this trades latency and call count for quota, it isn't a
+    /// pure win on both.
+    fn distance_matrix( &self, requests: &[&RouteRequest] ) -> Result<Vec<i64>, String> {
+        let first = requests[0];
+
+        let origins: Vec<&str>      = requests.iter().map( |r| r.origin ).collect();
+        let destinations: Vec<&str> = requests.iter().map( |r| r.destination ).collect();
+        let origins_param      = origins.join( "|" );
+        let destinations_param = destinations.join( "|" );
+
+        let mut params: Vec<(&str, &str)> = vec![
+            ("origins", &origins_param[..]),
+            ("destinations", &destinations_param[..]),
+            ("mode", first.mode),
+            ("key", self.settings.api_key() ),
+        ];
+
+        let ( time_params, duration_field ) = time_and_mode_params( first );
+        params.extend( time_params );
+
+        let response = try!( self.settings.get_with_params(
+            "https://maps.googleapis.com/maps/api/distancematrix/json",
+            &params ) );
+
+        let response_json = try!( Json::from_str( &response.body ).map_err( |e| format!( "{:?}", e ) ) );
+        let rows           = try!( response_json.as_object().and_then( |o| o.get( "rows" ) ).and_then( |r| r.as_array() ).ok_or( "malformed distance matrix response".to_string() ) );
+
+        let mut result = Vec::with_capacity( requests.len() );
+
+        // Each leg only cares about its own origin/destination pair, which sits on the diagonal
+        // of the rows[i].elements[j] grid since origins and destinations were built leg-by-leg.
+        for ( i, row ) in rows.iter().enumerate() {
+            let element = try!( row.as_object()
+                .and_then( |r| r.get( "elements" ) )
+                .and_then( |e| e.as_array() )
+                .and_then( |e| e.get( i ) )
+                .and_then( |e| e.as_object() )
+                .ok_or( "malformed distance matrix row".to_string() ) );
+
+            let duration = try!( element.get( duration_field ).ok_or( format!( "missing {}", duration_field ) ) );
+            let value    = try!( duration.as_object().and_then( |d| d.get( "value" ) ).and_then( |v| v.as_i64() ).ok_or( "missing duration value".to_string() ) );
+
+            result.push( value );
+        }
+
+        return Ok( result );
+    }
+}
+
+/// Builds the arrival/departure-time and transit-mode params shared by the Directions and
+/// Distance Matrix endpoints, along with which field (`duration` or `duration_in_traffic`) to
+/// read the result back from.
+///
+/// Google only returns `duration_in_traffic` when planning from a departure time in driving
+/// mode; an `arrival_time` plans backward instead and only ever comes back with a plain
+/// duration. Kept as one function so `directions` and `distance_matrix` can't silently diverge
+/// on this logic.
+fn time_and_mode_params<'a>( request: &RouteRequest<'a> ) -> ( Vec<(&'a str, &'a str)>, &'static str ) {
+    let mut params: Vec<(&str, &str)> = Vec::new();
+    let mut use_traffic_duration = false;
+
+    if let Some( arrival_time ) = request.arrival_time {
+        params.push( ("arrival_time", arrival_time) );
+    } else if let Some( departure_time ) = request.departure_time {
+        params.push( ("departure_time", departure_time) );
+
+        if request.mode == "driving" {
+            params.push( ("traffic_model", "best_guess") );
+            use_traffic_duration = true;
+        }
+    } else if request.mode == "driving" {
+        params.push( ("departure_time", "now") );
+        params.push( ("traffic_model", "best_guess") );
+        use_traffic_duration = true;
+    }
+
+    if let Some( transit_mode ) = request.transit_mode {
+        params.push( ("transit_mode", transit_mode) );
+    }
+
+    let duration_field = if use_traffic_duration { "duration_in_traffic" } else { "duration" };
+
+    return ( params, duration_field );
+}
+
+/// Constructs the `RouteProvider` named by a profile's `provider` field, configured with the
+/// given client settings.
+///
+/// Only `"google_maps"` is implemented today; other values (such as `"valhalla"` or `"otp"` for
+/// self-hosted Valhalla/OpenTripPlanner endpoints) are reserved for future back ends.
+pub fn provider_from_name( name: &str, settings: ClientSettings ) -> Result<Box<RouteProvider>, String> {
+    match name {
+        "google_maps"   => Ok( Box::new( GoogleMapsProvider::new( settings ) ) ),
+        other           => Err( format!( "unknown route provider '{}'", other ) ),
+    }
+}
+
+#[cfg( test )]
+mod tests {
+    use super::{RouteProvider, RouteRequest};
+
+    fn request( origin: &str ) -> RouteRequest {
+        return RouteRequest {
+            origin: origin,
+            destination: "destination",
+            via: "",
+            mode: "driving",
+            transit_mode: None,
+            departure_time: None,
+            arrival_time: None,
+        };
+    }
+
+    /// A trivial mock `RouteProvider`, standing in for a real routing back end in tests: the
+    /// whole point of the trait is that callers like `print_plan` only depend on it, not on
+    /// `GoogleMapsProvider`.
+    struct MockProvider;
+
+    impl RouteProvider for MockProvider {
+        fn leg_duration( &self, request: &RouteRequest ) -> Result<i64, String> {
+            return Ok( request.origin.len() as i64 );
+        }
+    }
+
+    #[test]
+    fn default_leg_durations_calls_leg_duration_per_request() {
+        let provider = MockProvider;
+        let requests = vec![ request( "a" ), request( "bb" ), request( "ccc" ) ];
+
+        assert_eq!( provider.leg_durations( &requests ).unwrap(), vec![ 1, 2, 3 ] );
+    }
+
+    #[test]
+    fn default_leg_geometry_reports_unsupported() {
+        let provider = MockProvider;
+
+        assert!( provider.leg_geometry( &request( "a" ) ).is_err() );
+    }
+
+    #[test]
+    fn default_leg_duration_and_geometry_propagates_unsupported_geometry() {
+        // MockProvider only overrides leg_duration, so the default leg_duration_and_geometry
+        // should surface leg_geometry's "unsupported" error rather than hiding it.
+        let provider = MockProvider;
+
+        assert!( provider.leg_duration_and_geometry( &request( "a" ) ).is_err() );
+    }
+}